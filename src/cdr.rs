@@ -22,7 +22,6 @@ pub struct CompressedVideo {
     #[allow(dead_code)]
     pub frame_id: String,
     pub data: Vec<u8>,
-    #[allow(dead_code)]
     pub format: String,
 }
 
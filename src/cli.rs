@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
 #[derive(Debug, Parser)]
 #[command(name = "mcap-foxglove-video-extract")]
@@ -15,4 +15,54 @@ pub struct Cli {
     /// Output directory
     #[arg(long, default_value = ".")]
     pub output: PathBuf,
+
+    /// Output container format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Mp4)]
+    pub format: OutputFormat,
+
+    /// Target duration in seconds of each HLS segment (only used with `--format hls`)
+    #[arg(long, default_value_t = 2)]
+    pub segment_duration: u64,
+
+    /// When extracting `all` topics, mux every track into one multi-track
+    /// MP4 instead of writing one file per topic (always writes an MP4;
+    /// `--format hls` is ignored when this is set)
+    #[arg(long)]
+    pub combine: bool,
+
+    /// Embed this non-video topic as a timed ONVIF metadata track alongside
+    /// the video (only used with `--format mp4`)
+    #[arg(long)]
+    pub metadata_topic: Option<String>,
+
+    /// Start of the extracted clip, in seconds relative to the topic's first
+    /// frame. Overridden by `--start-ns` if both are given (only used with
+    /// `--format mp4`)
+    #[arg(long)]
+    pub start: Option<f64>,
+
+    /// End of the extracted clip, in seconds relative to the topic's first
+    /// frame. Overridden by `--end-ns` if both are given (only used with
+    /// `--format mp4`)
+    #[arg(long)]
+    pub end: Option<f64>,
+
+    /// Start of the extracted clip, in nanoseconds relative to the topic's
+    /// first frame (only used with `--format mp4`)
+    #[arg(long)]
+    pub start_ns: Option<u64>,
+
+    /// End of the extracted clip, in nanoseconds relative to the topic's
+    /// first frame (only used with `--format mp4`)
+    #[arg(long)]
+    pub end_ns: Option<u64>,
+}
+
+/// Container produced per extracted topic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// A single `.mp4` file.
+    Mp4,
+    /// Fragmented MP4 segments plus an `m3u8` VOD playlist.
+    Hls,
 }
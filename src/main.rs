@@ -5,15 +5,17 @@ use std::{
     collections::{HashMap, HashSet},
     fs,
     path::Path,
+    sync::{Arc, Mutex},
 };
 
 use anyhow::{Context, Result};
 use cdr::decode_compressed_video;
 use clap::Parser;
-use cli::Cli;
+use cli::{Cli, OutputFormat};
 use gstreamer as gst;
 use gstreamer::prelude::*;
 use gstreamer_app as gst_app;
+use m3u8_rs::{MediaPlaylist, MediaPlaylistType, MediaSegment};
 use mcap::MessageStream;
 use memmap2::MmapOptions;
 
@@ -41,21 +43,82 @@ fn run() -> Result<()> {
             fs::create_dir_all(&args.output).with_context(|| {
                 format!("unable to create output dir {}", args.output.display())
             })?;
-            for topic in topics {
-                extract_video(&mapped, &topic, &args.output)?;
+            if args.combine {
+                if args.format == OutputFormat::Hls {
+                    eprintln!("Warning: --combine only produces an MP4; ignoring --format hls");
+                }
+                extract_video_combined(&mapped, &topics, &args.output)?;
+            } else {
+                for topic in topics {
+                    extract(&mapped, &topic, &args)?;
+                }
             }
         }
         Some(topic) => {
             fs::create_dir_all(&args.output).with_context(|| {
                 format!("unable to create output dir {}", args.output.display())
             })?;
-            extract_video(&mapped, topic, &args.output)?;
+            extract(&mapped, topic, &args)?;
         }
     }
 
     Ok(())
 }
 
+fn extract(mapped: &memmap2::Mmap, topic: &str, args: &Cli) -> Result<()> {
+    match args.format {
+        OutputFormat::Mp4 => extract_video(
+            mapped,
+            topic,
+            &args.output,
+            args.metadata_topic.as_deref(),
+            FrameRange::from_args(args),
+        ),
+        OutputFormat::Hls => {
+            if args.metadata_topic.is_some() {
+                eprintln!("Warning: --metadata-topic is ignored with --format hls");
+            }
+            if !FrameRange::from_args(args).is_unbounded() {
+                eprintln!("Warning: --start/--end are ignored with --format hls");
+            }
+            extract_video_hls(mapped, topic, &args.output, args.segment_duration)
+        }
+    }
+}
+
+/// Clip boundaries, in nanoseconds relative to a topic's first frame.
+#[derive(Debug, Clone, Copy)]
+struct FrameRange {
+    start_ns: u64,
+    end_ns: Option<u64>,
+}
+
+impl FrameRange {
+    fn unbounded() -> Self {
+        Self {
+            start_ns: 0,
+            end_ns: None,
+        }
+    }
+
+    fn is_unbounded(&self) -> bool {
+        self.start_ns == 0 && self.end_ns.is_none()
+    }
+
+    fn from_args(args: &Cli) -> Self {
+        let start_ns = args
+            .start_ns
+            .or_else(|| args.start.map(seconds_to_ns))
+            .unwrap_or(0);
+        let end_ns = args.end_ns.or_else(|| args.end.map(seconds_to_ns));
+        Self { start_ns, end_ns }
+    }
+}
+
+fn seconds_to_ns(seconds: f64) -> u64 {
+    (seconds.max(0.0) * 1_000_000_000.0) as u64
+}
+
 fn map_mcap(path: &Path) -> Result<memmap2::Mmap> {
     let file = fs::File::open(path)
         .with_context(|| format!("unable to open MCAP file {}", path.display()))?;
@@ -129,17 +192,34 @@ fn get_topic_durations(mapped: &memmap2::Mmap) -> Result<HashMap<String, u64>> {
     Ok(durations)
 }
 
-fn extract_video(mapped: &memmap2::Mmap, topic: &str, output_dir: &Path) -> Result<()> {
+fn extract_video(
+    mapped: &memmap2::Mmap,
+    topic: &str,
+    output_dir: &Path,
+    metadata_topic: Option<&str>,
+    range: FrameRange,
+) -> Result<()> {
     println!(
         "Extracting video from topic {topic} in {}",
         output_dir.display()
     );
     gst::init()?;
 
+    let codec = detect_video_codec(mapped, topic)?;
+    println!("Detected codec {codec:?} on topic {topic}");
+    let framerate = compute_framerate(mapped, topic)?;
+    println!(
+        "Detected framerate {}/{} on topic {topic}",
+        framerate.numer(),
+        framerate.denom()
+    );
+    let origin_ns = earliest_codec_matched_timestamp(mapped, topic, codec)?;
+
     let safe_topic = topic.replace('/', "_");
     let output_file = output_dir.join(format!("{safe_topic}.mp4"));
 
-    let (pipeline, appsrc) = build_pipeline(&output_file)?;
+    let (pipeline, appsrc, metadata_appsrc) =
+        build_pipeline(&output_file, codec, framerate, metadata_topic)?;
     let bus = pipeline.bus().context("pipeline missing bus")?;
 
     pipeline
@@ -150,53 +230,31 @@ fn extract_video(mapped: &memmap2::Mmap, topic: &str, output_dir: &Path) -> Resu
         &[gst::MessageType::StateChanged],
     );
 
-    let mut prev_publish: Option<u64> = None;
-    let mut frame_count = 0usize;
-
-    for msg in MessageStream::new(mapped)? {
-        let msg = msg?;
-        if !(is_video_message(&msg) && msg.channel.topic == topic) {
-            continue;
-        }
-
-        let video = match decode_compressed_video(msg.data.as_ref()) {
-            Ok(v) => v,
-            Err(err) => {
-                eprintln!("Failed to decode CDR message on {topic}: {err}");
-                continue;
-            }
-        };
-
-        let mut buffer = gst::Buffer::from_slice(video.data);
-        {
-            let buffer = buffer.get_mut().context("buffer not writable")?;
-
-            let duration_ns = prev_publish
-                .map(|prev| msg.publish_time.saturating_sub(prev).max(1))
-                .unwrap_or(1_000_000_000 / 30);
-            let pts = gst::ClockTime::from_nseconds(msg.publish_time);
-            buffer.set_pts(pts);
-            buffer.set_dts(pts);
-            buffer.set_duration(gst::ClockTime::from_nseconds(duration_ns));
-        }
+    let mut sources: Vec<Box<dyn FrameSource + '_>> = vec![Box::new(VideoTrackSource::new(
+        mapped, topic, codec, framerate, origin_ns, range, appsrc,
+    )?)];
+    if let (Some(metadata_topic), Some(metadata_appsrc)) = (metadata_topic, metadata_appsrc) {
+        sources.push(Box::new(MetadataTrackSource::new(
+            mapped,
+            metadata_topic,
+            origin_ns,
+            metadata_appsrc,
+        )?));
+    }
 
-        match appsrc.push_buffer(buffer) {
-            Ok(gst::FlowSuccess::Ok) => {
-                frame_count += 1;
-                prev_publish = Some(msg.publish_time);
-            }
-            Ok(other) => {
-                eprintln!("Unexpected flow return when pushing buffer: {other:?}");
-                break;
-            }
-            Err(err) => {
-                eprintln!("Failed to push buffer: {err}");
-                break;
-            }
+    let mut summaries = interleave_push(sources)?.into_iter();
+    let summary = summaries
+        .next()
+        .context("interleave_push returned no video summary")?;
+    if let Some(metadata_topic) = metadata_topic {
+        if let Some(metadata_summary) = summaries.next() {
+            println!(
+                "Embedded {} metadata frames from topic {metadata_topic}",
+                metadata_summary.frame_count
+            );
         }
     }
 
-    appsrc.end_of_stream().context("failed to signal EOS")?;
     let msg = bus.timed_pop_filtered(
         gst::ClockTime::from_seconds(30),
         &[gst::MessageType::Eos, gst::MessageType::Error],
@@ -208,7 +266,7 @@ fn extract_video(mapped: &memmap2::Mmap, topic: &str, output_dir: &Path) -> Resu
                 println!(
                     "Successfully finished writing {} ({} frames)",
                     output_file.display(),
-                    frame_count
+                    summary.frame_count
                 );
                 Ok(())
             }
@@ -227,13 +285,646 @@ fn extract_video(mapped: &memmap2::Mmap, topic: &str, output_dir: &Path) -> Resu
     res
 }
 
-fn build_pipeline(output_path: &Path) -> Result<(gst::Pipeline, gst_app::AppSrc)> {
-    let pipeline = gst::Pipeline::new();
+/// Outcome of streaming a topic's frames into an `appsrc`.
+struct PushSummary {
+    frame_count: usize,
+    /// Rebased PTS (nanoseconds from the topic's first frame) of the last
+    /// frame pushed.
+    last_pts_ns: u64,
+}
 
-    let caps = gst::Caps::builder("video/x-h264")
-        .field("stream-format", "byte-stream")
-        .field("framerate", gst::Fraction::new(30, 1))
-        .build();
+/// A single track's ordered buffer feed into its own `appsrc`, driven by
+/// `interleave_push` so multiple tracks can be advanced in timestamp order
+/// instead of one being drained to completion before the next starts.
+trait FrameSource {
+    /// Rebased PTS (nanoseconds) of the next buffer `pop` would return, or
+    /// `None` once the source is exhausted.
+    fn peek_ns(&self) -> Option<u64>;
+    /// Takes the next buffer. Only called while `peek_ns` is `Some`.
+    fn pop(&mut self) -> Result<Option<gst::Buffer>>;
+    fn appsrc(&self) -> &gst_app::AppSrc;
+}
+
+/// Pushes buffers from every source into their respective `appsrc`s in
+/// timestamp order, one buffer at a time across sources, rather than
+/// draining one source to completion before starting the next. `mp4mux`/
+/// `onvifmp4mux` collect one buffer per sink pad at a time, so draining a
+/// source fully first stalls its appsrc while the muxer waits on the other
+/// pads, and an unbounded amount of that source's data queues up in memory
+/// in the meantime. Each source's `appsrc` is EOS'd as soon as that source
+/// is exhausted.
+fn interleave_push(mut sources: Vec<Box<dyn FrameSource + '_>>) -> Result<Vec<PushSummary>> {
+    let mut frame_counts = vec![0usize; sources.len()];
+    let mut last_pts_ns = vec![0u64; sources.len()];
+    let mut eosed = vec![false; sources.len()];
+
+    loop {
+        let next_idx = sources
+            .iter()
+            .enumerate()
+            .filter_map(|(i, source)| source.peek_ns().map(|ts| (i, ts)))
+            .min_by_key(|&(_, ts)| ts)
+            .map(|(i, _)| i);
+
+        let Some(idx) = next_idx else { break };
+
+        let pts_ns = sources[idx].peek_ns().unwrap_or(0);
+        if let Some(buffer) = sources[idx].pop()? {
+            match sources[idx].appsrc().push_buffer(buffer) {
+                Ok(gst::FlowSuccess::Ok) => {
+                    frame_counts[idx] += 1;
+                    last_pts_ns[idx] = pts_ns;
+                }
+                Ok(other) => {
+                    eprintln!("Unexpected flow return when pushing buffer: {other:?}");
+                }
+                Err(err) => {
+                    eprintln!("Failed to push buffer: {err}");
+                }
+            }
+        }
+
+        if sources[idx].peek_ns().is_none() && !eosed[idx] {
+            sources[idx]
+                .appsrc()
+                .end_of_stream()
+                .context("failed to signal EOS")?;
+            eosed[idx] = true;
+        }
+    }
+
+    for (idx, source) in sources.iter().enumerate() {
+        if !eosed[idx] {
+            source
+                .appsrc()
+                .end_of_stream()
+                .context("failed to signal EOS")?;
+        }
+    }
+
+    Ok(frame_counts
+        .into_iter()
+        .zip(last_pts_ns)
+        .map(|(frame_count, last_pts_ns)| PushSummary {
+            frame_count,
+            last_pts_ns,
+        })
+        .collect())
+}
+
+/// Iterates a topic's `CompressedVideo` messages whose `format` matches
+/// `codec`, yielding `(capture_ns, data)` in MCAP log order. Frames that fail
+/// to decode or whose format disagrees with `codec` are logged and skipped.
+struct FilteredVideoIter<'a> {
+    topic: String,
+    codec: VideoCodec,
+    messages: MessageStream<'a>,
+}
+
+impl<'a> FilteredVideoIter<'a> {
+    fn new(mapped: &'a memmap2::Mmap, topic: &str, codec: VideoCodec) -> Result<Self> {
+        Ok(Self {
+            topic: topic.to_string(),
+            codec,
+            messages: MessageStream::new(mapped)?,
+        })
+    }
+}
+
+impl Iterator for FilteredVideoIter<'_> {
+    type Item = Result<(u64, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for msg in &mut self.messages {
+            let msg = match msg {
+                Ok(msg) => msg,
+                Err(err) => return Some(Err(err.into())),
+            };
+            if !(is_video_message(&msg) && msg.channel.topic == self.topic) {
+                continue;
+            }
+
+            let video = match decode_compressed_video(msg.data.as_ref()) {
+                Ok(v) => v,
+                Err(err) => {
+                    eprintln!("Failed to decode CDR message on {}: {err}", self.topic);
+                    continue;
+                }
+            };
+
+            if VideoCodec::from_format(&video.format) != Some(self.codec) {
+                eprintln!(
+                    "Skipping frame on {} whose format {:?} disagrees with detected codec {:?}",
+                    self.topic, video.format, self.codec
+                );
+                continue;
+            }
+
+            return Some(Ok((video.timestamp.as_nanos(), video.data)));
+        }
+        None
+    }
+}
+
+/// A `FrameSource` that streams one topic's codec-matched frames into its
+/// own `appsrc`, holding one frame back so each buffer's duration can be
+/// computed from its successor's timestamp (falling back to `framerate` for
+/// the last frame). When `range` narrows the clip, frames are collected and
+/// keyframe-trimmed up front by `collect_trimmed_frames`; otherwise they're
+/// streamed lazily straight off `FilteredVideoIter`.
+struct VideoTrackSource<'a> {
+    appsrc: gst_app::AppSrc,
+    fallback_duration_ns: u64,
+    origin_ns: u64,
+    frames: Box<dyn Iterator<Item = Result<(u64, Vec<u8>, bool)>> + 'a>,
+    current: Option<(u64, Vec<u8>, bool)>,
+    lookahead: Option<(u64, Vec<u8>, bool)>,
+}
+
+impl<'a> VideoTrackSource<'a> {
+    fn new(
+        mapped: &'a memmap2::Mmap,
+        topic: &str,
+        codec: VideoCodec,
+        framerate: gst::Fraction,
+        origin_ns: u64,
+        range: FrameRange,
+        appsrc: gst_app::AppSrc,
+    ) -> Result<Self> {
+        let mut frames: Box<dyn Iterator<Item = Result<(u64, Vec<u8>, bool)>> + 'a> =
+            if range.is_unbounded() {
+                Box::new(
+                    FilteredVideoIter::new(mapped, topic, codec)?
+                        .map(|item| item.map(|(capture_ns, data)| (capture_ns, data, false))),
+                )
+            } else {
+                Box::new(
+                    collect_trimmed_frames(mapped, topic, codec, origin_ns, range)?
+                        .into_iter()
+                        .map(Ok),
+                )
+            };
+
+        let current = frames.next().transpose()?;
+        let lookahead = frames.next().transpose()?;
+        Ok(Self {
+            appsrc,
+            fallback_duration_ns: duration_from_framerate(framerate),
+            origin_ns,
+            frames,
+            current,
+            lookahead,
+        })
+    }
+}
+
+impl FrameSource for VideoTrackSource<'_> {
+    fn peek_ns(&self) -> Option<u64> {
+        self.current
+            .as_ref()
+            .map(|(capture_ns, _, _)| capture_ns.saturating_sub(self.origin_ns))
+    }
+
+    fn appsrc(&self) -> &gst_app::AppSrc {
+        &self.appsrc
+    }
+
+    fn pop(&mut self) -> Result<Option<gst::Buffer>> {
+        let Some((capture_ns, data, decode_only)) = self.current.take() else {
+            return Ok(None);
+        };
+        let start_ns = capture_ns.saturating_sub(self.origin_ns);
+
+        let duration_ns = match &self.lookahead {
+            Some((next_ns, _, _)) => next_ns.saturating_sub(capture_ns).max(1),
+            None => self.fallback_duration_ns,
+        };
+
+        self.current = self.lookahead.take();
+        if self.current.is_some() {
+            self.lookahead = self.frames.next().transpose()?;
+        }
+
+        let mut buffer = gst::Buffer::from_slice(data);
+        {
+            let buffer = buffer.get_mut().context("buffer not writable")?;
+            let pts = gst::ClockTime::from_nseconds(start_ns);
+            buffer.set_pts(pts);
+            buffer.set_dts(pts);
+            buffer.set_duration(gst::ClockTime::from_nseconds(duration_ns));
+            if decode_only {
+                buffer.set_flags(gst::BufferFlags::DECODE_ONLY);
+            }
+        }
+        Ok(Some(buffer))
+    }
+}
+
+/// Collects `topic`'s codec-matched frames trimmed to `range`, keyframe-aware:
+/// starts at the last keyframe at or before `range.start_ns` (frames before
+/// `range.start_ns` are kept but flagged `DECODE_ONLY` so the decoder has a
+/// reference frame without displaying them) and stops at the first frame past
+/// `range.end_ns`, inclusive.
+fn collect_trimmed_frames(
+    mapped: &memmap2::Mmap,
+    topic: &str,
+    codec: VideoCodec,
+    origin_ns: u64,
+    range: FrameRange,
+) -> Result<Vec<(u64, Vec<u8>, bool)>> {
+    let mut frames = Vec::new();
+    for item in FilteredVideoIter::new(mapped, topic, codec)? {
+        frames.push(item?);
+    }
+    frames.sort_by_key(|(capture_ns, _)| *capture_ns);
+
+    if frames.is_empty() {
+        anyhow::bail!("no foxglove.CompressedVideo messages found on topic {topic}");
+    }
+
+    let last_start_ns = frames
+        .last()
+        .map(|(capture_ns, _)| capture_ns.saturating_sub(origin_ns))
+        .unwrap_or(0);
+    if range.start_ns > last_start_ns {
+        anyhow::bail!(
+            "--start ({} ns) is past topic {topic}'s last frame ({last_start_ns} ns); nothing to extract",
+            range.start_ns
+        );
+    }
+
+    let begin_idx = frames
+        .iter()
+        .rposition(|(capture_ns, data)| {
+            let start_ns = capture_ns.saturating_sub(origin_ns);
+            start_ns <= range.start_ns && is_keyframe(codec, data)
+        })
+        .unwrap_or(0);
+
+    let mut trimmed = Vec::new();
+    for (capture_ns, data) in frames.into_iter().skip(begin_idx) {
+        let start_ns = capture_ns.saturating_sub(origin_ns);
+        let decode_only = start_ns < range.start_ns;
+        let past_end = range.end_ns.is_some_and(|end_ns| start_ns > end_ns);
+        trimmed.push((capture_ns, data, decode_only));
+        if past_end {
+            break;
+        }
+    }
+
+    Ok(trimmed)
+}
+
+/// A `FrameSource` for a non-video topic embedded as a timed metadata track
+/// alongside the video, with PTS taken from each message's `log_time`
+/// (rebased to the video track's origin) and no computed duration, matching
+/// `onvifmp4mux`'s expectation that metadata samples are instants.
+struct MetadataTrackSource<'a> {
+    appsrc: gst_app::AppSrc,
+    origin_ns: u64,
+    topic: String,
+    messages: MessageStream<'a>,
+    current: Option<(u64, Vec<u8>)>,
+}
+
+impl<'a> MetadataTrackSource<'a> {
+    fn new(
+        mapped: &'a memmap2::Mmap,
+        topic: &str,
+        origin_ns: u64,
+        appsrc: gst_app::AppSrc,
+    ) -> Result<Self> {
+        let mut source = Self {
+            appsrc,
+            origin_ns,
+            topic: topic.to_string(),
+            messages: MessageStream::new(mapped)?,
+            current: None,
+        };
+        source.current = source.next_message()?;
+        Ok(source)
+    }
+
+    fn next_message(&mut self) -> Result<Option<(u64, Vec<u8>)>> {
+        for msg in &mut self.messages {
+            let msg = msg?;
+            if msg.channel.topic != self.topic {
+                continue;
+            }
+            return Ok(Some((msg.log_time, msg.data.as_ref().to_vec())));
+        }
+        Ok(None)
+    }
+}
+
+impl FrameSource for MetadataTrackSource<'_> {
+    fn peek_ns(&self) -> Option<u64> {
+        self.current
+            .as_ref()
+            .map(|(log_time, _)| log_time.saturating_sub(self.origin_ns))
+    }
+
+    fn appsrc(&self) -> &gst_app::AppSrc {
+        &self.appsrc
+    }
+
+    fn pop(&mut self) -> Result<Option<gst::Buffer>> {
+        let Some((log_time, data)) = self.current.take() else {
+            return Ok(None);
+        };
+        self.current = self.next_message()?;
+
+        let mut buffer = gst::Buffer::from_slice(data);
+        {
+            let buffer = buffer.get_mut().context("buffer not writable")?;
+            let pts = gst::ClockTime::from_nseconds(log_time.saturating_sub(self.origin_ns));
+            buffer.set_pts(pts);
+            buffer.set_dts(pts);
+        }
+        Ok(Some(buffer))
+    }
+}
+
+/// Whether `data` (in `codec`'s wire format) starts a new GOP, i.e. can be
+/// decoded without any preceding frame.
+fn is_keyframe(codec: VideoCodec, data: &[u8]) -> bool {
+    match codec {
+        VideoCodec::H264 => h264_is_keyframe(data),
+        VideoCodec::H265 => h265_is_keyframe(data),
+        VideoCodec::Vp8 => vp8_is_keyframe(data),
+        VideoCodec::Vp9 => vp9_is_keyframe(data),
+        VideoCodec::Av1 => av1_is_keyframe(data),
+        // JPEG frames are intra-only.
+        VideoCodec::Jpeg => true,
+    }
+}
+
+/// Iterates over Annex-B NAL units (`00 00 01` / `00 00 00 01` start-code
+/// delimited), yielding each unit's payload (start code excluded).
+fn iter_annexb_nals(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    // Each entry is (start-code begin, payload start) so a unit's end can be
+    // computed from the *next* unit's start-code begin, not its payload
+    // start, which would otherwise leave the next start code's bytes
+    // attached to this unit's tail.
+    let mut markers = Vec::new();
+    let mut i = 0;
+    while i + 2 < data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            let code_begin = if i > 0 && data[i - 1] == 0 { i - 1 } else { i };
+            markers.push((code_begin, i + 3));
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    let ends: Vec<usize> = markers
+        .iter()
+        .skip(1)
+        .map(|&(code_begin, _)| code_begin)
+        .chain(std::iter::once(data.len()))
+        .collect();
+    markers
+        .into_iter()
+        .map(|(_, payload_start)| payload_start)
+        .zip(ends)
+        .map(move |(start, end)| &data[start..end])
+}
+
+fn h264_is_keyframe(data: &[u8]) -> bool {
+    iter_annexb_nals(data).any(|nal| nal.first().is_some_and(|byte| byte & 0x1F == 5))
+}
+
+fn h265_is_keyframe(data: &[u8]) -> bool {
+    iter_annexb_nals(data).any(|nal| {
+        nal.first()
+            .is_some_and(|byte| matches!((byte >> 1) & 0x3F, 16..=23))
+    })
+}
+
+fn vp8_is_keyframe(data: &[u8]) -> bool {
+    data.first().is_some_and(|byte| byte & 0x01 == 0)
+}
+
+/// Reads the uncompressed VP9 frame header's `frame_marker`/`profile` bits,
+/// then `show_existing_frame` and `frame_type`. Layout (MSB first):
+/// `frame_marker(2) profile_low(1) profile_high(1) [reserved_zero(1)]
+/// show_existing_frame(1) frame_type(1) ...` — `reserved_zero` is only
+/// present for profile 3, shifting the later bits down by one for profile 3.
+fn vp9_is_keyframe(data: &[u8]) -> bool {
+    let Some(&first) = data.first() else {
+        return false;
+    };
+    let profile_low = (first >> 5) & 0x1;
+    let profile_high = (first >> 4) & 0x1;
+    let profile = (profile_high << 1) | profile_low;
+
+    // Profile 3 carries an extra `reserved_zero` bit before
+    // `show_existing_frame`, shifting everything after it down by one bit.
+    let show_existing_frame_bit = if profile == 3 { 2 } else { 3 };
+    let show_existing_frame = (first >> show_existing_frame_bit) & 0x1;
+    if show_existing_frame == 1 {
+        return false;
+    }
+    let frame_type = (first >> (show_existing_frame_bit - 1)) & 0x1;
+    frame_type == 0
+}
+
+fn read_leb128(data: &[u8]) -> Option<(u64, usize)> {
+    let mut value: u64 = 0;
+    for (i, &byte) in data.iter().enumerate().take(8) {
+        value |= u64::from(byte & 0x7F) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// Walks AV1 OBUs looking for a frame or frame-header OBU, reading its
+/// `show_existing_frame`/`frame_type` bits to decide whether it's a keyframe.
+fn av1_is_keyframe(data: &[u8]) -> bool {
+    let mut pos = 0;
+    while pos < data.len() {
+        let header = data[pos];
+        let obu_type = (header >> 3) & 0x0F;
+        let extension_flag = (header >> 2) & 0x1 != 0;
+        let has_size_field = (header >> 1) & 0x1 != 0;
+        let mut offset = pos + 1;
+        if extension_flag {
+            offset += 1;
+        }
+        let obu_size = if has_size_field {
+            let Some((size, leb_len)) = read_leb128(&data[offset..]) else {
+                return false;
+            };
+            offset += leb_len;
+            size as usize
+        } else {
+            data.len().saturating_sub(offset)
+        };
+
+        // OBU_FRAME_HEADER (3) or OBU_FRAME (6) carry `frame_type`.
+        if matches!(obu_type, 3 | 6) {
+            let Some(&payload_first) = data.get(offset) else {
+                return false;
+            };
+            let show_existing_frame = (payload_first >> 7) & 0x1 != 0;
+            if show_existing_frame {
+                return false;
+            }
+            let frame_type = (payload_first >> 5) & 0x3;
+            return frame_type == 0; // KEY_FRAME
+        }
+
+        pos = offset + obu_size;
+    }
+    false
+}
+
+/// Scans `topic`'s frames and derives a framerate from the median
+/// inter-frame capture interval, reused from the same `MessageStream`
+/// iteration pattern as `get_topic_durations`.
+fn compute_framerate(mapped: &memmap2::Mmap, topic: &str) -> Result<gst::Fraction> {
+    let fallback_fps = gst::Fraction::new(30, 1);
+
+    let mut timestamps = Vec::new();
+    for msg in MessageStream::new(mapped)? {
+        let msg = msg?;
+        if !(is_video_message(&msg) && msg.channel.topic == topic) {
+            continue;
+        }
+
+        let Ok(video) = decode_compressed_video(msg.data.as_ref()) else {
+            continue;
+        };
+        timestamps.push(video.timestamp.as_nanos());
+    }
+    timestamps.sort_unstable();
+
+    Ok(framerate_from_timestamps(&timestamps).unwrap_or(fallback_fps))
+}
+
+/// Derives a framerate from the median inter-frame delta of `timestamps`
+/// (already sorted, nanoseconds), or `None` if fewer than two timestamps are
+/// given.
+fn framerate_from_timestamps(timestamps: &[u64]) -> Option<gst::Fraction> {
+    let mut deltas: Vec<u64> = timestamps
+        .windows(2)
+        .map(|pair| pair[1].saturating_sub(pair[0]).max(1))
+        .collect();
+    if deltas.is_empty() {
+        return None;
+    }
+    deltas.sort_unstable();
+
+    let median_ns = if deltas.len() % 2 == 1 {
+        deltas[deltas.len() / 2]
+    } else {
+        (deltas[deltas.len() / 2 - 1] + deltas[deltas.len() / 2]) / 2
+    };
+
+    Some(reduced_fraction(1_000_000_000, median_ns))
+}
+
+fn duration_from_framerate(framerate: gst::Fraction) -> u64 {
+    1_000_000_000u64 * framerate.denom() as u64 / framerate.numer() as u64
+}
+
+fn reduced_fraction(numerator: u64, denominator: u64) -> gst::Fraction {
+    let divisor = gcd(numerator, denominator).max(1);
+    gst::Fraction::new(
+        (numerator / divisor) as i32,
+        (denominator / divisor) as i32,
+    )
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Codec carried by a `foxglove.CompressedVideo` message, as named in its
+/// `format` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VideoCodec {
+    H264,
+    H265,
+    Vp8,
+    Vp9,
+    Av1,
+    Jpeg,
+}
+
+impl VideoCodec {
+    fn from_format(format: &str) -> Option<Self> {
+        match format {
+            "h264" => Some(Self::H264),
+            "h265" | "hevc" => Some(Self::H265),
+            "vp8" => Some(Self::Vp8),
+            "vp9" => Some(Self::Vp9),
+            "av1" => Some(Self::Av1),
+            "jpeg" => Some(Self::Jpeg),
+            _ => None,
+        }
+    }
+
+    fn caps(&self) -> gst::Caps {
+        match self {
+            Self::H264 => gst::Caps::builder("video/x-h264")
+                .field("stream-format", "byte-stream")
+                .build(),
+            Self::H265 => gst::Caps::builder("video/x-h265")
+                .field("stream-format", "byte-stream")
+                .build(),
+            Self::Vp8 => gst::Caps::builder("video/x-vp8").build(),
+            Self::Vp9 => gst::Caps::builder("video/x-vp9").build(),
+            Self::Av1 => gst::Caps::builder("video/x-av1").build(),
+            Self::Jpeg => gst::Caps::builder("image/jpeg").build(),
+        }
+    }
+
+    /// Parser element required between the appsrc and the muxer, if any.
+    fn parser(&self) -> Option<&'static str> {
+        match self {
+            Self::H264 => Some("h264parse"),
+            Self::H265 => Some("h265parse"),
+            Self::Vp8 | Self::Vp9 => None,
+            Self::Av1 => Some("av1parse"),
+            Self::Jpeg => Some("jpegparse"),
+        }
+    }
+}
+
+/// Scans `topic` for the first decodable `foxglove.CompressedVideo` message
+/// and returns the codec named in its `format` field.
+fn detect_video_codec(mapped: &memmap2::Mmap, topic: &str) -> Result<VideoCodec> {
+    for msg in MessageStream::new(mapped)? {
+        let msg = msg?;
+        if !(is_video_message(&msg) && msg.channel.topic == topic) {
+            continue;
+        }
+
+        let Ok(video) = decode_compressed_video(msg.data.as_ref()) else {
+            continue;
+        };
+
+        return VideoCodec::from_format(&video.format).with_context(|| {
+            format!(
+                "unsupported CompressedVideo format {:?} on topic {topic}",
+                video.format
+            )
+        });
+    }
+
+    anyhow::bail!("no foxglove.CompressedVideo messages found on topic {topic}")
+}
+
+/// Builds the `appsrc` shared by the MP4 and HLS pipelines, with caps
+/// selected for `codec` and the detected `framerate`.
+fn build_appsrc(codec: VideoCodec, framerate: gst::Fraction) -> Result<gst_app::AppSrc> {
+    let mut caps = codec.caps();
+    caps.get_mut()
+        .context("caps not writable")?
+        .set("framerate", framerate);
 
     let appsrc = gst_app::AppSrc::builder()
         .name("src")
@@ -244,32 +935,487 @@ fn build_pipeline(output_path: &Path) -> Result<(gst::Pipeline, gst_app::AppSrc)
         .build();
     appsrc.set_do_timestamp(true);
 
-    let h264parse = gst::ElementFactory::make("h264parse")
+    Ok(appsrc)
+}
+
+/// Builds the MP4 pipeline for `codec`. When `metadata_topic` is set, the
+/// muxer is swapped for `onvifmp4mux` and a second `appsrc` carrying a
+/// `application/x-onvif-metadata` track is linked to its own request pad, so
+/// the returned metadata `appsrc` is `Some` iff a metadata track was added.
+fn build_pipeline(
+    output_path: &Path,
+    codec: VideoCodec,
+    framerate: gst::Fraction,
+    metadata_topic: Option<&str>,
+) -> Result<(gst::Pipeline, gst_app::AppSrc, Option<gst_app::AppSrc>)> {
+    let pipeline = gst::Pipeline::new();
+    let appsrc = build_appsrc(codec, framerate)?;
+
+    let mut elements: Vec<gst::Element> = vec![appsrc.clone().upcast()];
+    if let Some(parser_name) = codec.parser() {
+        let parser = gst::ElementFactory::make(parser_name)
+            .build()
+            .with_context(|| format!("missing {parser_name} element"))?;
+        elements.push(parser);
+    }
+
+    let mux_factory = if metadata_topic.is_some() {
+        "onvifmp4mux"
+    } else {
+        "mp4mux"
+    };
+    let mux = gst::ElementFactory::make(mux_factory)
+        .property("faststart", true)
+        .build()
+        .with_context(|| format!("missing {mux_factory} element"))?;
+    let filesink = gst::ElementFactory::make("filesink")
+        .property("location", output_path.to_string_lossy().to_string())
         .build()
-        .context("missing h264parse element")?;
+        .context("missing filesink element")?;
+    elements.push(mux.clone());
+    elements.push(filesink);
+
+    let element_refs: Vec<&gst::Element> = elements.iter().collect();
+    pipeline.add_many(element_refs.iter().copied())?;
+    gst::Element::link_many(element_refs)?;
+
+    let metadata_appsrc = match metadata_topic {
+        Some(_) => {
+            let caps = gst::Caps::builder("application/x-onvif-metadata").build();
+            let metadata_appsrc = gst_app::AppSrc::builder()
+                .name("meta-src")
+                .caps(&caps)
+                .is_live(false)
+                .do_timestamp(false)
+                .format(gst::Format::Time)
+                .build();
+            pipeline.add(metadata_appsrc.upcast_ref::<gst::Element>())?;
+
+            let sink_pad = mux
+                .request_pad_simple("sink_%u")
+                .context("onvifmp4mux rejected a metadata request pad")?;
+            let src_pad = metadata_appsrc
+                .static_pad("src")
+                .context("metadata appsrc missing a src pad")?;
+            src_pad
+                .link(&sink_pad)
+                .context("failed to link metadata track to onvifmp4mux")?;
+
+            Some(metadata_appsrc)
+        }
+        None => None,
+    };
+
+    Ok((pipeline, appsrc, metadata_appsrc))
+}
+
+/// Earliest `CompressedVideo.timestamp` across all of `topics`, used as the
+/// shared zero origin for a combined multi-track recording.
+fn earliest_capture_timestamp(mapped: &memmap2::Mmap, topics: &HashSet<String>) -> Result<u64> {
+    let mut earliest: Option<u64> = None;
+
+    for msg in MessageStream::new(mapped)? {
+        let msg = msg?;
+        if !(is_video_message(&msg) && topics.contains(&msg.channel.topic)) {
+            continue;
+        }
+
+        let Ok(video) = decode_compressed_video(msg.data.as_ref()) else {
+            continue;
+        };
+        let ts = video.timestamp.as_nanos();
+        earliest = Some(earliest.map_or(ts, |e| e.min(ts)));
+    }
+
+    earliest.context("no foxglove.CompressedVideo messages found")
+}
+
+/// Earliest `CompressedVideo.timestamp` on `topic` among frames whose
+/// `format` matches `codec`, used as `extract_video`'s PTS-zero origin.
+/// `earliest_capture_timestamp` scans every decodable frame regardless of
+/// format, so a topic with an earlier frame in a different (unsupported)
+/// format would otherwise push the real first frame off of PTS 0 — the same
+/// codec mismatch `FilteredVideoIter` already logs and skips.
+fn earliest_codec_matched_timestamp(
+    mapped: &memmap2::Mmap,
+    topic: &str,
+    codec: VideoCodec,
+) -> Result<u64> {
+    let mut earliest: Option<u64> = None;
+
+    for msg in MessageStream::new(mapped)? {
+        let msg = msg?;
+        if !(is_video_message(&msg) && msg.channel.topic == topic) {
+            continue;
+        }
+
+        let Ok(video) = decode_compressed_video(msg.data.as_ref()) else {
+            continue;
+        };
+        if VideoCodec::from_format(&video.format) != Some(codec) {
+            continue;
+        }
+
+        let ts = video.timestamp.as_nanos();
+        earliest = Some(earliest.map_or(ts, |e| e.min(ts)));
+    }
+
+    earliest.with_context(|| format!("no foxglove.CompressedVideo messages found on topic {topic}"))
+}
+
+/// Muxes every topic in `topics` into a single MP4 with one video track per
+/// topic, all rebased onto a shared zero origin so the tracks share a
+/// timeline. Each topic gets its own `appsrc` (+ parser, if its codec needs
+/// one) linked to its own `mp4mux` request sink pad, so per-track codecs can
+/// differ.
+fn extract_video_combined(
+    mapped: &memmap2::Mmap,
+    topics: &HashSet<String>,
+    output_dir: &Path,
+) -> Result<()> {
+    println!(
+        "Extracting combined multi-track video in {}",
+        output_dir.display()
+    );
+    gst::init()?;
+
+    let mut topics: Vec<String> = topics.iter().cloned().collect();
+    topics.sort();
+
+    let origin_ns = earliest_capture_timestamp(mapped, &topics.iter().cloned().collect())?;
+
+    let output_file = output_dir.join("combined.mp4");
+    let pipeline = gst::Pipeline::new();
+
     let mp4mux = gst::ElementFactory::make("mp4mux")
         .property("faststart", true)
         .build()
         .context("missing mp4mux element")?;
     let filesink = gst::ElementFactory::make("filesink")
-        .property("location", output_path.to_string_lossy().to_string())
+        .property("location", output_file.to_string_lossy().to_string())
         .build()
         .context("missing filesink element")?;
+    pipeline.add_many([mp4mux.as_ref(), filesink.as_ref()])?;
+    mp4mux.link(&filesink).context("failed to link mp4mux to filesink")?;
 
-    pipeline.add_many([
-        appsrc.upcast_ref::<gst::Element>(),
-        h264parse.as_ref(),
-        mp4mux.as_ref(),
-        filesink.as_ref(),
-    ])?;
-    gst::Element::link_many([
-        appsrc.upcast_ref::<gst::Element>(),
-        h264parse.as_ref(),
-        mp4mux.as_ref(),
-        filesink.as_ref(),
-    ])?;
+    let mut tracks = Vec::new();
+    for topic in &topics {
+        let codec = detect_video_codec(mapped, topic)?;
+        let framerate = compute_framerate(mapped, topic)?;
+        println!(
+            "Detected codec {codec:?} at {}/{} fps on topic {topic}",
+            framerate.numer(),
+            framerate.denom()
+        );
 
-    Ok((pipeline, appsrc))
+        let appsrc = build_appsrc(codec, framerate)?;
+        pipeline.add(appsrc.upcast_ref::<gst::Element>())?;
+
+        let mut chain: Vec<gst::Element> = vec![appsrc.clone().upcast()];
+        if let Some(parser_name) = codec.parser() {
+            let parser = gst::ElementFactory::make(parser_name)
+                .build()
+                .with_context(|| format!("missing {parser_name} element"))?;
+            pipeline.add(&parser)?;
+            chain.push(parser);
+        }
+        for pair in chain.windows(2) {
+            pair[0].link(&pair[1])?;
+        }
+
+        let sink_pad = mp4mux
+            .request_pad_simple("sink_%u")
+            .with_context(|| format!("mp4mux rejected a request pad for topic {topic}"))?;
+        let src_pad = chain
+            .last()
+            .context("track chain is never empty")?
+            .static_pad("src")
+            .context("track's last element missing a src pad")?;
+        src_pad
+            .link(&sink_pad)
+            .context("failed to link track to mp4mux request pad")?;
+
+        tracks.push((topic.clone(), codec, framerate, appsrc));
+    }
+
+    let bus = pipeline.bus().context("pipeline missing bus")?;
+    pipeline
+        .set_state(gst::State::Playing)
+        .context("failed to start pipeline")?;
+    let _ = bus.timed_pop_filtered(
+        gst::ClockTime::from_seconds(5),
+        &[gst::MessageType::StateChanged],
+    );
+
+    let sources: Vec<Box<dyn FrameSource + '_>> = tracks
+        .iter()
+        .map(|(topic, codec, framerate, appsrc)| {
+            VideoTrackSource::new(
+                mapped,
+                topic,
+                *codec,
+                *framerate,
+                origin_ns,
+                FrameRange::unbounded(),
+                appsrc.clone(),
+            )
+            .map(|source| Box::new(source) as Box<dyn FrameSource + '_>)
+        })
+        .collect::<Result<_>>()?;
+    let total_frames: usize = interleave_push(sources)?
+        .iter()
+        .map(|summary| summary.frame_count)
+        .sum();
+
+    let msg = bus.timed_pop_filtered(
+        gst::ClockTime::from_seconds(30),
+        &[gst::MessageType::Eos, gst::MessageType::Error],
+    );
+
+    let res = match msg {
+        Some(message) => match message.view() {
+            gst::MessageView::Eos(_) => Ok(()),
+            gst::MessageView::Error(err) => {
+                Err(anyhow::anyhow!("GStreamer error: {}", err.error()))
+            }
+            _ => Err(anyhow::anyhow!("No EOS message received before timeout")),
+        },
+        None => Err(anyhow::anyhow!("No EOS message received before timeout")),
+    };
+
+    pipeline
+        .set_state(gst::State::Null)
+        .context("failed to tear down pipeline")?;
+    res?;
+
+    println!(
+        "Successfully finished writing {} ({} tracks, {} frames)",
+        output_file.display(),
+        tracks.len(),
+        total_frames
+    );
+
+    Ok(())
+}
+
+/// One fragment written by `splitmuxsink`, named relative to its segment
+/// directory, with the PTS at which it starts.
+struct HlsSegment {
+    uri: String,
+    start_ns: u64,
+}
+
+fn extract_video_hls(
+    mapped: &memmap2::Mmap,
+    topic: &str,
+    output_dir: &Path,
+    segment_duration: u64,
+) -> Result<()> {
+    println!(
+        "Extracting HLS segments from topic {topic} in {}",
+        output_dir.display()
+    );
+    gst::init()?;
+
+    let codec = detect_video_codec(mapped, topic)?;
+    println!("Detected codec {codec:?} on topic {topic}");
+    let framerate = compute_framerate(mapped, topic)?;
+    println!(
+        "Detected framerate {}/{} on topic {topic}",
+        framerate.numer(),
+        framerate.denom()
+    );
+
+    let safe_topic = topic.replace('/', "_");
+    let segment_dir = output_dir.join(safe_topic);
+    fs::create_dir_all(&segment_dir)
+        .with_context(|| format!("unable to create segment dir {}", segment_dir.display()))?;
+
+    let (pipeline, appsrc, segments) =
+        build_hls_pipeline(&segment_dir, codec, framerate, segment_duration)?;
+    let bus = pipeline.bus().context("pipeline missing bus")?;
+
+    pipeline
+        .set_state(gst::State::Playing)
+        .context("failed to start pipeline")?;
+    let _ = bus.timed_pop_filtered(
+        gst::ClockTime::from_seconds(5),
+        &[gst::MessageType::StateChanged],
+    );
+
+    let origin_ns = earliest_codec_matched_timestamp(mapped, topic, codec)?;
+    let source: Box<dyn FrameSource + '_> = Box::new(VideoTrackSource::new(
+        mapped,
+        topic,
+        codec,
+        framerate,
+        origin_ns,
+        FrameRange::unbounded(),
+        appsrc,
+    )?);
+    let summary = interleave_push(vec![source])?
+        .into_iter()
+        .next()
+        .context("interleave_push returned no summary")?;
+    let frame_count = summary.frame_count;
+    let last_pts_ns = summary.last_pts_ns;
+
+    let msg = bus.timed_pop_filtered(
+        gst::ClockTime::from_seconds(30),
+        &[gst::MessageType::Eos, gst::MessageType::Error],
+    );
+
+    let res = match msg {
+        Some(message) => match message.view() {
+            gst::MessageView::Eos(_) => Ok(()),
+            gst::MessageView::Error(err) => {
+                Err(anyhow::anyhow!("GStreamer error: {}", err.error()))
+            }
+            _ => Err(anyhow::anyhow!("No EOS message received before timeout")),
+        },
+        None => Err(anyhow::anyhow!("No EOS message received before timeout")),
+    };
+
+    pipeline
+        .set_state(gst::State::Null)
+        .context("failed to tear down pipeline")?;
+    res?;
+
+    let segments = segments.lock().expect("segment list mutex poisoned");
+    write_hls_playlist(&segment_dir, &segments, last_pts_ns, framerate)?;
+    println!(
+        "Successfully finished writing {} segments to {} ({} frames)",
+        segments.len(),
+        segment_dir.display(),
+        frame_count
+    );
+
+    Ok(())
+}
+
+fn build_hls_pipeline(
+    segment_dir: &Path,
+    codec: VideoCodec,
+    framerate: gst::Fraction,
+    segment_duration: u64,
+) -> Result<(gst::Pipeline, gst_app::AppSrc, Arc<Mutex<Vec<HlsSegment>>>)> {
+    let pipeline = gst::Pipeline::new();
+    let appsrc = build_appsrc(codec, framerate)?;
+
+    let mut elements: Vec<gst::Element> = vec![appsrc.clone().upcast()];
+    if let Some(parser_name) = codec.parser() {
+        let parser = gst::ElementFactory::make(parser_name)
+            .build()
+            .with_context(|| format!("missing {parser_name} element"))?;
+        elements.push(parser);
+    }
+
+    // Fragmented output is a qtmux/mp4mux feature: a non-zero
+    // `fragment-duration` (here the segment duration, in ms) makes it emit
+    // `moof`/`mdat` fragments instead of a single trailing `moov`.
+    // splitmuxsink starts a new `.m4s` file at the first keyframe once
+    // `max-size-time` (the segment duration) has been reached.
+    let muxer_properties = gst::Structure::new(
+        "properties",
+        &[
+            ("fragment-duration", &((segment_duration * 1000) as u32)),
+            ("streamable", &true),
+        ],
+    );
+
+    let segments: Arc<Mutex<Vec<HlsSegment>>> = Arc::new(Mutex::new(Vec::new()));
+    let segments_cb = segments.clone();
+    let segment_dir = segment_dir.to_path_buf();
+
+    let splitmuxsink = gst::ElementFactory::make("splitmuxsink")
+        .property("muxer-factory", "mp4mux")
+        .property("muxer-properties", muxer_properties)
+        .property("max-size-time", segment_duration * 1_000_000_000)
+        .property("send-keyframe-requests", true)
+        .build()
+        .context("missing splitmuxsink element")?;
+
+    splitmuxsink.connect("format-location-full", false, move |values| {
+        let fragment_id = values[1].get::<u32>().unwrap_or(0);
+        let start_ns = values[2]
+            .get::<gst::Sample>()
+            .ok()
+            .and_then(|sample| sample.buffer().map(|b| b.to_owned()))
+            .and_then(|buffer| buffer.pts())
+            .map(gst::ClockTime::nseconds)
+            .unwrap_or(0);
+
+        let filename = format!("segment_{:05}.m4s", fragment_id + 1);
+        segments_cb
+            .lock()
+            .expect("segment list mutex poisoned")
+            .push(HlsSegment {
+                uri: filename.clone(),
+                start_ns,
+            });
+
+        Some(gst::glib::Value::from(
+            segment_dir.join(&filename).to_string_lossy().as_ref(),
+        ))
+    });
+
+    elements.push(splitmuxsink);
+
+    let element_refs: Vec<&gst::Element> = elements.iter().collect();
+    pipeline.add_many(element_refs.iter().copied())?;
+    gst::Element::link_many(element_refs)?;
+
+    Ok((pipeline, appsrc, segments))
+}
+
+/// Writes `manifest.m3u8` for the fragments recorded in `segments`, with
+/// per-segment durations computed from the MCAP timestamp span each
+/// fragment covers. The final segment has no successor to bound it, so its
+/// end is `last_pts_ns` (the last frame's *start* timestamp) plus one
+/// `framerate`-derived frame interval, rather than `last_pts_ns` alone, which
+/// would under-report the last segment's duration by about one frame period.
+fn write_hls_playlist(
+    segment_dir: &Path,
+    segments: &[HlsSegment],
+    last_pts_ns: u64,
+    framerate: gst::Fraction,
+) -> Result<()> {
+    let mut playlist = MediaPlaylist {
+        version: Some(6),
+        playlist_type: Some(MediaPlaylistType::Vod),
+        end_list: true,
+        ..Default::default()
+    };
+
+    let last_frame_end_ns = last_pts_ns + duration_from_framerate(framerate);
+    for (index, segment) in segments.iter().enumerate() {
+        let end_ns = segments
+            .get(index + 1)
+            .map(|next| next.start_ns)
+            .unwrap_or(last_frame_end_ns.max(segment.start_ns));
+        let duration_secs = (end_ns.saturating_sub(segment.start_ns)) as f32 / 1_000_000_000.0;
+
+        playlist.segments.push(MediaSegment {
+            uri: segment.uri.clone(),
+            duration: duration_secs,
+            ..Default::default()
+        });
+    }
+
+    playlist.target_duration = playlist
+        .segments
+        .iter()
+        .fold(0.0_f32, |max, segment| max.max(segment.duration))
+        .ceil();
+
+    let manifest_path = segment_dir.join("manifest.m3u8");
+    let mut file = fs::File::create(&manifest_path)
+        .with_context(|| format!("unable to create {}", manifest_path.display()))?;
+    playlist
+        .write_to(&mut file)
+        .context("failed to write m3u8 playlist")?;
+
+    Ok(())
 }
 
 fn is_video_message(msg: &mcap::Message<'_>) -> bool {
@@ -279,3 +1425,83 @@ fn is_video_message(msg: &mcap::Message<'_>) -> bool {
         .map(|schema| schema.name == MESSAGE_SCHEMA_NAME)
         .unwrap_or(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn annexb_nals_strip_both_start_code_lengths() {
+        let data = [0, 0, 0, 1, 0xAA, 0xBB, 0, 0, 1, 0xCC, 0xDD, 0xEE];
+        let nals: Vec<&[u8]> = iter_annexb_nals(&data).collect();
+        assert_eq!(nals, vec![&[0xAA, 0xBB][..], &[0xCC, 0xDD, 0xEE][..]]);
+    }
+
+    #[test]
+    fn h264_detects_idr_vs_non_idr() {
+        assert!(h264_is_keyframe(&[0, 0, 0, 1, 0x65, 0xAA]));
+        assert!(!h264_is_keyframe(&[0, 0, 0, 1, 0x61, 0xAA]));
+    }
+
+    #[test]
+    fn h265_detects_idr_vs_trailing() {
+        assert!(h265_is_keyframe(&[0, 0, 0, 1, 0x26, 0x01]));
+        assert!(!h265_is_keyframe(&[0, 0, 0, 1, 0x02, 0x01]));
+    }
+
+    #[test]
+    fn vp8_detects_key_vs_inter_frame() {
+        assert!(vp8_is_keyframe(&[0x00, 0x00, 0x00]));
+        assert!(!vp8_is_keyframe(&[0x01, 0x00, 0x00]));
+    }
+
+    #[test]
+    fn vp9_detects_key_vs_inter_frame_profile0() {
+        // frame_marker=10, profile=0, show_existing_frame=0, frame_type=0 (key)
+        assert!(vp9_is_keyframe(&[0x80]));
+        // frame_marker=10, profile=0, show_existing_frame=0, frame_type=1 (inter)
+        assert!(!vp9_is_keyframe(&[0x84]));
+    }
+
+    #[test]
+    fn vp9_detects_key_vs_inter_frame_profile3() {
+        // profile=3, reserved_zero=0, show_existing_frame=0, frame_type=0 (key)
+        assert!(vp9_is_keyframe(&[0xB0]));
+        // profile=3, reserved_zero=0, show_existing_frame=0, frame_type=1 (inter)
+        assert!(!vp9_is_keyframe(&[0xB2]));
+    }
+
+    #[test]
+    fn vp9_show_existing_frame_is_never_a_keyframe() {
+        // frame_marker=10, profile=0, show_existing_frame=1
+        assert!(!vp9_is_keyframe(&[0x88]));
+    }
+
+    #[test]
+    fn av1_detects_key_vs_inter_obu_frame() {
+        // OBU_FRAME header, has_size_field, 1-byte payload: frame_type=KEY_FRAME
+        assert!(av1_is_keyframe(&[0x32, 0x01, 0x00]));
+        // Same, but frame_type=INTER_FRAME
+        assert!(!av1_is_keyframe(&[0x32, 0x01, 0x20]));
+    }
+
+    #[test]
+    fn jpeg_is_always_a_keyframe() {
+        assert!(is_keyframe(VideoCodec::Jpeg, &[]));
+    }
+
+    #[test]
+    fn framerate_from_timestamps_uses_median_delta() {
+        // Deltas of 33ms, 33ms, 1000ms (outlier) -> median is 33ms, i.e. ~30fps.
+        let timestamps = [0u64, 33_000_000, 66_000_000, 1_066_000_000];
+        let framerate = framerate_from_timestamps(&timestamps).expect("enough timestamps");
+        let fps = framerate.numer() as f64 / framerate.denom() as f64;
+        assert!((fps - 30.0).abs() < 0.5, "expected ~30fps, got {fps}");
+    }
+
+    #[test]
+    fn framerate_from_timestamps_needs_at_least_two_points() {
+        assert!(framerate_from_timestamps(&[]).is_none());
+        assert!(framerate_from_timestamps(&[42]).is_none());
+    }
+}